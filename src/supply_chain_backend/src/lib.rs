@@ -12,6 +12,14 @@ use validator::Validate;
 type Memory = VirtualMemory<DefaultMemoryImpl>;
 type IdCell = Cell<u64, Memory>;
 
+// Write a full Checkpoint for an order every this-many events appended for
+// it, bounding how many deltas `get_order_at` ever has to replay.
+const KEEP_STATE_EVERY: u64 = 64;
+
+// Upper bound on a `list_orders` page, so a caller-supplied `limit` can't
+// drive the response's `Vec::with_capacity` reservation arbitrarily high.
+const MAX_LIST_ORDERS_LIMIT: u32 = 500;
+
 // Define a struct for the 'Client'
 #[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
 struct Client {
@@ -46,24 +54,162 @@ struct Order {
     supplier_id: Option<u64>,
     item_types: Vec<String>,
     products: HashMap<String, u64>,
-    is_complete: bool,
+    status: OrderStatus,
     created_at: u64,
     updated_at: Option<u64>,
 }
 
-// Implement the 'Storable' trait for 'Client', 'Supplier', and 'Order'
-impl Storable for Client {
-    // Conversion to bytes
+// The lifecycle a real order moves through, replacing the old `is_complete`
+// flag which could only express "not done yet" vs "done".
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Debug)]
+enum OrderStatus {
+    Pending,
+    Assigned,
+    Shipped,
+    Delivered,
+    Cancelled,
+    Disputed,
+}
+
+impl Default for OrderStatus {
+    fn default() -> Self {
+        OrderStatus::Pending
+    }
+}
+
+// The legal transition table for `transition_order`. `Cancelled` is
+// terminal, and a `Delivered` order can only be reopened into `Disputed`;
+// every other status can still move to `Cancelled`.
+fn _is_valid_transition(from: OrderStatus, to: OrderStatus) -> bool {
+    use OrderStatus::*;
+    matches!(
+        (from, to),
+        (Pending, Assigned)
+            | (Assigned, Shipped)
+            | (Shipped, Delivered)
+            | (Shipped, Disputed)
+            | (Delivered, Disputed)
+            | (Disputed, Shipped)
+            | (Disputed, Delivered)
+            | (Pending, Cancelled)
+            | (Assigned, Cancelled)
+            | (Shipped, Cancelled)
+            | (Disputed, Cancelled)
+    )
+}
+
+// 'Client', 'Supplier', and 'Order' are persisted as raw candid bytes (see
+// `StoredBytes` below) rather than storing them directly as `StableBTreeMap`
+// values, so they no longer need `Storable`/`BoundedStorable` impls of their
+// own: decoding happens through `decode_record`, which returns a `Result`
+// instead of trapping on a corrupted entry.
+
+// The on-disk representation for 'Client', 'Supplier', and 'Order' rows.
+// Storing raw bytes instead of the decoded struct means a corrupted entry
+// can be decoded with `candid::decode_one` (which returns a `Result`) at the
+// call site, instead of through `Storable::from_bytes`, which must return
+// `Self` unconditionally and therefore can only trap on malformed input.
+#[derive(Clone, Default)]
+struct StoredBytes(Vec<u8>);
+
+impl Storable for StoredBytes {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Borrowed(&self.0)
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        StoredBytes(bytes.into_owned())
+    }
+}
+
+impl BoundedStorable for StoredBytes {
+    const MAX_SIZE: u32 = 1024;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// Encodes `value` for storage. Encoding a well-formed in-memory value cannot
+// fail the way decoding a persisted (and potentially corrupted) one can, so
+// this stays infallible like the rest of the codebase's `Encode!` call sites.
+fn encode_record<T: candid::CandidType>(value: &T) -> StoredBytes {
+    StoredBytes(Encode!(value).unwrap())
+}
+
+// Decodes a stored record, reporting a corrupted entry as `Error::Corruption`
+// instead of trapping: `candid::decode_one` returns a `Result`, unlike
+// `Storable::from_bytes`, whose `Decode!(..).unwrap()` traps on malformed bytes.
+fn decode_record<T>(bytes: &StoredBytes) -> Result<T, Error>
+where
+    T: candid::CandidType + for<'de> candid::Deserialize<'de>,
+{
+    candid::decode_one(&bytes.0).map_err(|e| Error::Corruption {
+        msg: format!("stable storage entry is corrupted and could not be decoded: {}", e),
+    })
+}
+
+// Composite keys for the order secondary indexes below. Deriving `Ord` on
+// these sorts first by the leading field and then by `order_id`, so a range
+// scan bounded by `order_id: 0..=u64::MAX` for a fixed leading field(s) walks
+// exactly the matching rows instead of the whole `ORDERS` table. The index
+// value is the `order_id` again (redundant with the key) so the map can
+// stick to a `Storable` type the crate already supports.
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Default)]
+struct ClientOrderKey {
+    client_id: u64,
+    order_id: u64,
+}
+
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Default)]
+struct SupplierOrderKey {
+    supplier_id: u64,
+    is_delivered: bool,
+    order_id: u64,
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Default)]
+struct ItemTypeOrderKey {
+    item_type: String,
+    order_id: u64,
+}
+
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Default)]
+struct CompletionOrderKey {
+    is_delivered: bool,
+    order_id: u64,
+}
+
+impl Storable for ClientOrderKey {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for ClientOrderKey {
+    // `Encode!` candid-encodes the struct, not the raw packed fields, so the
+    // bound has to cover the DIDL header and type table too: a measured
+    // `Encode!(&ClientOrderKey{..})` comes out to 37 bytes, not 2×u64=16.
+    const MAX_SIZE: u32 = 64;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+impl Storable for SupplierOrderKey {
     fn to_bytes(&self) -> Cow<[u8]> {
         Cow::Owned(Encode!(self).unwrap())
     }
-    // Conversion from bytes
     fn from_bytes(bytes: Cow<[u8]>) -> Self {
         Decode!(bytes.as_ref(), Self).unwrap()
     }
 }
 
-impl Storable for Supplier {
+impl BoundedStorable for SupplierOrderKey {
+    // Measured `Encode!(&SupplierOrderKey{..})` is 43 bytes once the DIDL
+    // header/type table are included, not the packed 3-field size.
+    const MAX_SIZE: u32 = 64;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+impl Storable for ItemTypeOrderKey {
     fn to_bytes(&self) -> Cow<[u8]> {
         Cow::Owned(Encode!(self).unwrap())
     }
@@ -72,7 +218,12 @@ impl Storable for Supplier {
     }
 }
 
-impl Storable for Order {
+impl BoundedStorable for ItemTypeOrderKey {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+impl Storable for CompletionOrderKey {
     fn to_bytes(&self) -> Cow<[u8]> {
         Cow::Owned(Encode!(self).unwrap())
     }
@@ -81,19 +232,102 @@ impl Storable for Order {
     }
 }
 
-// Implement the 'BoundedStorable' trait for 'Client', 'Supplier', and 'Order'
-impl BoundedStorable for Client {
-    const MAX_SIZE: u32 = 1024;
+impl BoundedStorable for CompletionOrderKey {
+    // Measured `Encode!(&CompletionOrderKey{..})` is 30 bytes once the DIDL
+    // header/type table are included, not the packed 2-field size.
+    const MAX_SIZE: u32 = 48;
     const IS_FIXED_SIZE: bool = false;
 }
 
-impl BoundedStorable for Supplier {
-    const MAX_SIZE: u32 = 1024;
+// Composite key for the order event log and its checkpoints: sorting by
+// `order_id` first then `seq` lets a single order's history, or the latest
+// checkpoint at or before a target sequence, be found with a range scan.
+#[derive(candid::CandidType, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Default)]
+struct OrderEventKey {
+    order_id: u64,
+    seq: u64,
+}
+
+impl Storable for OrderEventKey {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for OrderEventKey {
+    // Measured `Encode!(&OrderEventKey{..})` is 36 bytes once the DIDL
+    // header/type table are included, not the packed 2×u64 size.
+    const MAX_SIZE: u32 = 64;
     const IS_FIXED_SIZE: bool = false;
 }
 
-impl BoundedStorable for Order {
-    const MAX_SIZE: u32 = 1024;
+// The kind of change an `OrderEvent` recorded, for an auditor reading the log.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+enum OrderEventKind {
+    Created,
+    SupplierAssigned,
+    StatusChanged,
+    Updated,
+    Deleted,
+}
+
+impl Default for OrderEventKind {
+    fn default() -> Self {
+        OrderEventKind::Created
+    }
+}
+
+// A single append-only entry in an order's provenance log. `snapshot_delta`
+// holds the order's full state right after the change — simpler than a true
+// field-level diff, and still cheap to replay since checkpoints keep the
+// replay window bounded to `KEEP_STATE_EVERY` events.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct OrderEvent {
+    seq: u64,
+    order_id: u64,
+    timestamp: u64,
+    kind: OrderEventKind,
+    snapshot_delta: Order,
+}
+
+impl Storable for OrderEvent {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for OrderEvent {
+    const MAX_SIZE: u32 = 2048;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// A full snapshot of an order's state at `seq`, written every
+// `KEEP_STATE_EVERY` events so history can be replayed from a recent
+// baseline instead of from the beginning of time.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)]
+struct Checkpoint {
+    seq: u64,
+    order_id: u64,
+    state: Order,
+}
+
+impl Storable for Checkpoint {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap())
+    }
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl BoundedStorable for Checkpoint {
+    const MAX_SIZE: u32 = 2048;
     const IS_FIXED_SIZE: bool = false;
 }
 
@@ -108,20 +342,82 @@ thread_local! {
             .expect("Cannot create a counter")
     );
 
-    static CLIENT_STORAGE: RefCell<StableBTreeMap<u64, Client, Memory>> =
+    static CLIENT_STORAGE: RefCell<StableBTreeMap<u64, StoredBytes, Memory>> =
         RefCell::new(StableBTreeMap::init(
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)))
     ));
 
-    static SUPPLIER_STORAGE: RefCell<StableBTreeMap<u64, Supplier, Memory>> =
+    static SUPPLIER_STORAGE: RefCell<StableBTreeMap<u64, StoredBytes, Memory>> =
         RefCell::new(StableBTreeMap::init(
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2)))
     ));
 
-    static ORDERS: RefCell<StableBTreeMap<u64, Order, Memory>> =
+    static ORDERS: RefCell<StableBTreeMap<u64, StoredBytes, Memory>> =
         RefCell::new(StableBTreeMap::init(
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3)))
     ));
+
+    // Secondary indexes over ORDERS, keyed for prefix range scans (see the
+    // composite key types above) so queries touch only the matching rows.
+    static CLIENT_ORDER_INDEX: RefCell<StableBTreeMap<ClientOrderKey, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4)))
+    ));
+
+    static SUPPLIER_ORDER_INDEX: RefCell<StableBTreeMap<SupplierOrderKey, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(5)))
+    ));
+
+    static ITEM_TYPE_ORDER_INDEX: RefCell<StableBTreeMap<ItemTypeOrderKey, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(6)))
+    ));
+
+    static COMPLETION_ORDER_INDEX: RefCell<StableBTreeMap<CompletionOrderKey, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(7)))
+    ));
+
+    // Append-only order provenance log, its per-order index, periodic
+    // checkpoints, and the counters that drive when a checkpoint is written.
+    static EVENT_SEQ_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(8))), 0)
+            .expect("Cannot create a counter")
+    );
+
+    static ORDER_EVENTS: RefCell<StableBTreeMap<u64, OrderEvent, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(9)))
+    ));
+
+    static ORDER_EVENT_INDEX: RefCell<StableBTreeMap<OrderEventKey, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(10)))
+    ));
+
+    static ORDER_CHECKPOINTS: RefCell<StableBTreeMap<OrderEventKey, Checkpoint, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(11)))
+    ));
+
+    static ORDER_EVENTS_SINCE_CHECKPOINT: RefCell<StableBTreeMap<u64, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(12)))
+    ));
+}
+
+// Increments and returns the pre-increment value of the global ID counter,
+// reporting storage corruption instead of trapping if the write fails.
+fn next_id() -> Result<u64, Error> {
+    ID_COUNTER
+        .with(|counter| {
+            let current_id = *counter.borrow().get();
+            counter.borrow_mut().set(current_id + 1)
+        })
+        .map_err(|_| Error::Corruption {
+            msg: "failed to persist the id counter".to_string(),
+        })
 }
 
 // Define structs for payload data (used in update calls)
@@ -154,7 +450,6 @@ struct OrderPayload {
     supplier_id: u64,
     products: HashMap<String, u64>,
     items_types: Vec<String>,
-    is_complete: bool,
 }
 
 #[derive(candid::CandidType, Deserialize, Serialize, Default)]
@@ -163,10 +458,40 @@ struct AddOrderSupplierPayload {
     supplier_id: u64,
 }
 
+// Narrows a `list_orders` page to a single dimension. Kept flat (one
+// optional criterion) rather than a struct of independent filters, mirroring
+// how the single-purpose get_*_orders query functions each pick one axis.
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)]
+enum OrderFilter {
+    All,
+    Client(u64),
+    Supplier(u64),
+    Status(OrderStatus),
+}
+
+impl Default for OrderFilter {
+    fn default() -> Self {
+        OrderFilter::All
+    }
+}
+
+#[derive(candid::CandidType, Deserialize, Serialize, Default)]
+struct ListQuery {
+    start_after: Option<u64>,
+    limit: u32,
+    filter: OrderFilter,
+}
+
+#[derive(candid::CandidType, Serialize, Deserialize, Default)]
+struct OrderPage {
+    items: Vec<Order>,
+    next_cursor: Option<u64>,
+}
+
 // Define query function to get a client by ID
 #[ic_cdk::query]
 fn get_client(id: u64) -> Result<Client, Error> {
-    match _get_client(&id) {
+    match _get_client(&id)? {
         Some(client) => Ok(client),
         None => Err(Error::NotFound {
             msg: format!("client id:{} does not exist", id),
@@ -183,12 +508,7 @@ fn add_client(payload: ClientPayload) -> Result<Client, Error> {
             msg: check_payload.unwrap_err().to_string(),
         });
     }
-    let id = ID_COUNTER
-        .with(|counter| {
-            let current_id = *counter.borrow().get();
-            counter.borrow_mut().set(current_id + 1)
-        })
-        .expect("Cannot increment Ids");
+    let id = next_id()?;
 
     let client = Client {
         id,
@@ -206,20 +526,23 @@ fn add_client(payload: ClientPayload) -> Result<Client, Error> {
 }
 
 // Helper function to get a client by ID
-fn _get_client(id: &u64) -> Option<Client> {
-    CLIENT_STORAGE.with(|clients| clients.borrow().get(&id))
+fn _get_client(id: &u64) -> Result<Option<Client>, Error> {
+    match CLIENT_STORAGE.with(|clients| clients.borrow().get(id)) {
+        Some(bytes) => decode_record(&bytes).map(Some),
+        None => Ok(None),
+    }
 }
 
 // Helper function to insert a client
 fn _insert_client(client: &Client) {
-    CLIENT_STORAGE.with(|clients| clients.borrow_mut().insert(client.id, client.clone()));
+    CLIENT_STORAGE.with(|clients| clients.borrow_mut().insert(client.id, encode_record(client)));
 }
 
 // Supplier
 #[ic_cdk::query]
 fn get_supplier(id: u64) -> Result<Supplier, Error> {
     // Try to get the supplier with the given id
-    match _get_supplier(&id) {
+    match _get_supplier(&id)? {
         Some(supplier) => Ok(supplier), // Return the supplier if found
         None => Err(Error::NotFound {
             msg: format!("supplier id:{} does not exist", id),
@@ -230,12 +553,12 @@ fn get_supplier(id: u64) -> Result<Supplier, Error> {
 #[ic_cdk::query]
 fn get_suppliers() -> Result<Vec<Supplier>, Error> {
     // Retrieve all suppliers from the storage
-    let suppliers_map: Vec<(u64, Supplier)> =
+    let suppliers_map: Vec<(u64, StoredBytes)> =
         SUPPLIER_STORAGE.with(|service| service.borrow().iter().collect());
-    let suppliers: Vec<Supplier> = suppliers_map
-        .into_iter()
-        .map(|(_, supplier)| supplier)
-        .collect();
+    let mut suppliers: Vec<Supplier> = Vec::with_capacity(suppliers_map.len());
+    for (_, bytes) in suppliers_map {
+        suppliers.push(decode_record(&bytes)?);
+    }
 
     if !suppliers.is_empty() {
         Ok(suppliers) // Return the list of suppliers if not empty
@@ -255,12 +578,7 @@ fn add_supplier(payload: SupplierPayload) -> Result<Supplier, Error> {
         });
     }
     // Increment the global ID counter to get a new ID for the supplier
-    let id = ID_COUNTER
-        .with(|counter| {
-            let current_id = *counter.borrow().get();
-            counter.borrow_mut().set(current_id + 1)
-        })
-        .expect("Cannot increment Ids");
+    let id = next_id()?;
 
     // Create a new Supplier with the provided payload and the generated ID
     let supplier = Supplier {
@@ -282,14 +600,18 @@ fn add_supplier(payload: SupplierPayload) -> Result<Supplier, Error> {
 
 // Supplier Helper functions
 
-fn _get_supplier(id: &u64) -> Option<Supplier> {
+fn _get_supplier(id: &u64) -> Result<Option<Supplier>, Error> {
     // Get the supplier from the storage based on the provided ID
-    SUPPLIER_STORAGE.with(|suppliers| suppliers.borrow().get(&id))
+    match SUPPLIER_STORAGE.with(|suppliers| suppliers.borrow().get(id)) {
+        Some(bytes) => decode_record(&bytes).map(Some),
+        None => Ok(None),
+    }
 }
 
 fn _insert_supplier(supplier: &Supplier) {
     // Insert a supplier into the storage
-    SUPPLIER_STORAGE.with(|suppliers| suppliers.borrow_mut().insert(supplier.id, supplier.clone()));
+    SUPPLIER_STORAGE
+        .with(|suppliers| suppliers.borrow_mut().insert(supplier.id, encode_record(supplier)));
 }
 
 // Orders
@@ -297,7 +619,7 @@ fn _insert_supplier(supplier: &Supplier) {
 #[ic_cdk::query]
 fn get_order(id: u64) -> Result<Order, Error> {
     // Try to get the order with the given ID
-    match _get_order(&id) {
+    match _get_order(&id)? {
         Some(order) => Ok(order), // Return the order if found
         None => Err(Error::NotFound {
             msg: format!("order id:{} does not exist", id),
@@ -308,8 +630,12 @@ fn get_order(id: u64) -> Result<Order, Error> {
 #[ic_cdk::query]
 fn get_orders() -> Result<Vec<Order>, Error> {
     // Retrieve all orders from the storage
-    let orders_map: Vec<(u64, Order)> = ORDERS.with(|service| service.borrow().iter().collect());
-    let orders: Vec<Order> = orders_map.into_iter().map(|(_, order)| order).collect();
+    let orders_map: Vec<(u64, StoredBytes)> =
+        ORDERS.with(|service| service.borrow().iter().collect());
+    let mut orders: Vec<Order> = Vec::with_capacity(orders_map.len());
+    for (_, bytes) in orders_map {
+        orders.push(decode_record(&bytes)?);
+    }
 
     if !orders.is_empty() {
         Ok(orders) // Return the list of orders if not empty
@@ -320,15 +646,52 @@ fn get_orders() -> Result<Vec<Order>, Error> {
     }
 }
 
+// Paginated alternative to `get_orders`: a bounded range scan starting just
+// after `start_after` so callers can walk the whole table page by page
+// instead of pulling every row into one response.
+#[ic_cdk::query]
+fn list_orders(query: ListQuery) -> OrderPage {
+    let limit = query.limit.clamp(1, MAX_LIST_ORDERS_LIMIT) as usize;
+    let start = query.start_after.map(|id| id + 1).unwrap_or(0);
+
+    let mut items = Vec::with_capacity(limit);
+    let mut last_included = None;
+    let mut next_cursor = None;
+
+    ORDERS.with(|orders| {
+        for (order_id, bytes) in orders.borrow().range(start..) {
+            // `list_orders` has no error channel to report a corrupted entry
+            // through, so a bad record is skipped rather than surfaced.
+            let Ok(order) = decode_record::<Order>(&bytes) else {
+                continue;
+            };
+            if !_order_matches_filter(&order, &query.filter) {
+                continue;
+            }
+            if items.len() == limit {
+                next_cursor = last_included;
+                break;
+            }
+            items.push(order);
+            last_included = Some(order_id);
+        }
+    });
+
+    OrderPage { items, next_cursor }
+}
+
+fn _order_matches_filter(order: &Order, filter: &OrderFilter) -> bool {
+    match filter {
+        OrderFilter::All => true,
+        OrderFilter::Client(client_id) => order.client_id == *client_id,
+        OrderFilter::Supplier(supplier_id) => order.supplier_id == Some(*supplier_id),
+        OrderFilter::Status(status) => order.status == *status,
+    }
+}
+
 #[ic_cdk::query]
 fn get_incomplete_orders() -> Result<Vec<Order>, Error> {
-    // Retrieve all orders from the storage
-    let orders_map: Vec<(u64, Order)> = ORDERS.with(|service| service.borrow().iter().collect());
-    let orders: Vec<Order> = orders_map
-        .into_iter()
-        .map(|(_, order)| order)
-        .filter(|order| !order.is_complete)
-        .collect();
+    let orders = _orders_for_ids(_completion_order_ids(false))?;
 
     if !orders.is_empty() {
         Ok(orders) // Return the list of orders if not empty
@@ -349,19 +712,18 @@ fn get_supplier_preferred_orders(supplier_id: u64) -> Result<Vec<Order>, Error>
     // Retrieve the supplier's preferred items
     let preferred_items = _get_supplier_preferred_items(supplier_id);
 
-    // Retrieve all orders from the storage
-    let orders_map: Vec<(u64, Order)> = ORDERS.with(|service| service.borrow().iter().collect());
-
-    // Filter the orders to only include those where the order_types match the supplier's preferred_items
-    let orders: Vec<Order> = orders_map
-        .into_iter()
-        .map(|(_, order)| order)
-        .filter(|order| {
-            preferred_items
-                .iter()
-                .any(|item| order.item_types.contains(item))
-        })
-        .collect();
+    // An order can match more than one preferred item, so dedupe order ids
+    // across the per-item_type index scans before fetching the rows.
+    let mut seen = std::collections::HashSet::new();
+    let mut order_ids = vec![];
+    for item in &preferred_items {
+        for order_id in _item_type_order_ids(item) {
+            if seen.insert(order_id) {
+                order_ids.push(order_id);
+            }
+        }
+    }
+    let orders = _orders_for_ids(order_ids)?;
 
     if !orders.is_empty() {
         Ok(orders) // Return the list of orders if not empty
@@ -377,25 +739,21 @@ fn get_supplier_preferred_orders(supplier_id: u64) -> Result<Vec<Order>, Error>
 
 fn _get_supplier_preferred_items(supplier_id: u64) -> Vec<String> {
     // Retrieve the supplier from the storage
-    let supplier = SUPPLIER_STORAGE.with(|suppliers| suppliers.borrow().get(&supplier_id));
+    let supplier = SUPPLIER_STORAGE
+        .with(|suppliers| suppliers.borrow().get(&supplier_id))
+        .and_then(|bytes| decode_record::<Supplier>(&bytes).ok());
 
     // Return the supplier's preferred items if the supplier is found
     if let Some(supplier) = supplier {
         supplier.prefered_items
     } else {
-        vec![] // Return an empty vector if the supplier is not found
+        vec![] // Return an empty vector if the supplier is not found or corrupted
     }
 }
 
 #[ic_cdk::query]
 fn get_completed_orders() -> Result<Vec<Order>, Error> {
-    // Retrieve all orders from the storage
-    let orders_map: Vec<(u64, Order)> = ORDERS.with(|service| service.borrow().iter().collect());
-    let orders: Vec<Order> = orders_map
-        .into_iter()
-        .map(|(_, order)| order)
-        .filter(|order| order.is_complete)
-        .collect();
+    let orders = _orders_for_ids(_completion_order_ids(true))?;
 
     if !orders.is_empty() {
         Ok(orders) // Return the list of orders if not empty
@@ -414,13 +772,7 @@ fn get_client_orders(client_id: u64) -> Result<Vec<Order>, Error> {
         });
     }
 
-    // Retrieve all orders from the storage
-    let orders_map: Vec<(u64, Order)> = ORDERS.with(|service| service.borrow().iter().collect());
-    let orders: Vec<Order> = orders_map
-        .into_iter()
-        .map(|(_, order)| order)
-        .filter(|order| order.client_id == client_id)
-        .collect();
+    let orders = _orders_for_ids(_client_order_ids(client_id))?;
 
     if !orders.is_empty() {
         Ok(orders) // Return the list of orders if not empty
@@ -438,13 +790,8 @@ fn get_supplier_orders(supplier_id: u64) -> Result<Vec<Order>, Error> {
             msg: format!("Supplier with id={} not found.", supplier_id),
         });
     }
-    // Retrieve all orders from the storage
-    let orders_map: Vec<(u64, Order)> = ORDERS.with(|service| service.borrow().iter().collect());
-    let orders: Vec<Order> = orders_map
-        .into_iter()
-        .map(|(_, order)| order)
-        .filter(|order| order.supplier_id == Some(supplier_id))
-        .collect();
+
+    let orders = _orders_for_ids(_supplier_order_ids(supplier_id, None))?;
 
     if !orders.is_empty() {
         Ok(orders) // Return the list of orders if not empty
@@ -462,13 +809,8 @@ fn get_supplier_completed_orders(supplier_id: u64) -> Result<Vec<Order>, Error>
             msg: format!("Supplier with id={} not found.", supplier_id),
         });
     }
-    // Retrieve all orders from the storage
-    let orders_map: Vec<(u64, Order)> = ORDERS.with(|service| service.borrow().iter().collect());
-    let orders: Vec<Order> = orders_map
-        .into_iter()
-        .map(|(_, order)| order)
-        .filter(|order| order.supplier_id == Some(supplier_id) && order.is_complete)
-        .collect();
+
+    let orders = _orders_for_ids(_supplier_order_ids(supplier_id, Some(true)))?;
 
     if !orders.is_empty() {
         Ok(orders) // Return the list of orders if not empty
@@ -481,6 +823,101 @@ fn get_supplier_completed_orders(supplier_id: u64) -> Result<Vec<Order>, Error>
         }) // Return an error if no orders are found
     }
 }
+
+// Prefix range scan of CLIENT_ORDER_INDEX for a single client_id.
+fn _client_order_ids(client_id: u64) -> Vec<u64> {
+    let start = ClientOrderKey {
+        client_id,
+        order_id: 0,
+    };
+    let end = ClientOrderKey {
+        client_id,
+        order_id: u64::MAX,
+    };
+    CLIENT_ORDER_INDEX.with(|index| {
+        index
+            .borrow()
+            .range(start..=end)
+            .map(|(_, order_id)| order_id)
+            .collect()
+    })
+}
+
+// Prefix range scan of SUPPLIER_ORDER_INDEX for a supplier, optionally
+// narrowed to delivered (`Some(true)`) or not-yet-delivered (`Some(false)`) orders.
+fn _supplier_order_ids(supplier_id: u64, is_delivered: Option<bool>) -> Vec<u64> {
+    let (start_delivered, end_delivered) = match is_delivered {
+        Some(flag) => (flag, flag),
+        None => (false, true),
+    };
+    let start = SupplierOrderKey {
+        supplier_id,
+        is_delivered: start_delivered,
+        order_id: 0,
+    };
+    let end = SupplierOrderKey {
+        supplier_id,
+        is_delivered: end_delivered,
+        order_id: u64::MAX,
+    };
+    SUPPLIER_ORDER_INDEX.with(|index| {
+        index
+            .borrow()
+            .range(start..=end)
+            .map(|(_, order_id)| order_id)
+            .collect()
+    })
+}
+
+// Prefix range scan of ITEM_TYPE_ORDER_INDEX for a single item type.
+fn _item_type_order_ids(item_type: &str) -> Vec<u64> {
+    let start = ItemTypeOrderKey {
+        item_type: item_type.to_string(),
+        order_id: 0,
+    };
+    let end = ItemTypeOrderKey {
+        item_type: item_type.to_string(),
+        order_id: u64::MAX,
+    };
+    ITEM_TYPE_ORDER_INDEX.with(|index| {
+        index
+            .borrow()
+            .range(start..=end)
+            .map(|(_, order_id)| order_id)
+            .collect()
+    })
+}
+
+// Prefix range scan of COMPLETION_ORDER_INDEX for delivered/not-yet-delivered orders.
+fn _completion_order_ids(is_delivered: bool) -> Vec<u64> {
+    let start = CompletionOrderKey {
+        is_delivered,
+        order_id: 0,
+    };
+    let end = CompletionOrderKey {
+        is_delivered,
+        order_id: u64::MAX,
+    };
+    COMPLETION_ORDER_INDEX.with(|index| {
+        index
+            .borrow()
+            .range(start..=end)
+            .map(|(_, order_id)| order_id)
+            .collect()
+    })
+}
+
+// Resolves a list of order ids (as produced by an index scan) into the
+// matching orders, surfacing storage corruption instead of dropping it.
+fn _orders_for_ids(order_ids: Vec<u64>) -> Result<Vec<Order>, Error> {
+    let mut orders = Vec::with_capacity(order_ids.len());
+    for order_id in order_ids {
+        if let Some(order) = _get_order(&order_id)? {
+            orders.push(order);
+        }
+    }
+    Ok(orders)
+}
 // Function to create an order
 #[ic_cdk::update]
 fn add_order(payload: OrderPayload) -> Result<Order, Error> {
@@ -496,12 +933,7 @@ fn add_order(payload: OrderPayload) -> Result<Order, Error> {
         });
     }
     // Increment the global ID counter to get a new ID for the order
-    let id = ID_COUNTER
-        .with(|counter| {
-            let current_id = *counter.borrow().get();
-            counter.borrow_mut().set(current_id + 1)
-        })
-        .expect("Cannot increment Ids");
+    let id = next_id()?;
 
     // Create a new Order with the provided payload and the generated ID
     let order = Order {
@@ -511,22 +943,30 @@ fn add_order(payload: OrderPayload) -> Result<Order, Error> {
         supplier_id: None,
         products: payload.products,
         item_types: payload.items_types,
-        is_complete: false,
+        status: OrderStatus::Pending,
         created_at: time(),
         updated_at: None,
     };
 
     // Insert the new order into the storage
     _insert_order(&order);
+    record_order_event(order.id, OrderEventKind::Created, &order)?;
 
     Ok(order) // Return the newly added order
 }
 
+// Creates many orders in one call. Each payload is validated and applied
+// independently, so one bad entry doesn't abort the rest of the batch.
+#[ic_cdk::update]
+fn batch_add_orders(payloads: Vec<OrderPayload>) -> Vec<Result<Order, Error>> {
+    payloads.into_iter().map(add_order).collect()
+}
+
 // Function to add a suplier for an order
 #[ic_cdk::update]
 fn add_order_supplier(payload: AddOrderSupplierPayload) -> Result<Order, Error> {
     // Try to get the order with the given ID
-    match ORDERS.with(|service| service.borrow().get(&payload.order_id)) {
+    match _get_order(&payload.order_id)? {
         Some(mut order) => {
             if !is_supplier_id_valid(&payload.supplier_id) {
                 return Err(Error::NotFound {
@@ -539,6 +979,7 @@ fn add_order_supplier(payload: AddOrderSupplierPayload) -> Result<Order, Error>
 
             // Insert the updated order back into the storage
             _insert_order(&order);
+            record_order_event(order.id, OrderEventKind::SupplierAssigned, &order)?;
 
             Ok(order) // Return the updated order
         }
@@ -554,9 +995,9 @@ fn add_order_supplier(payload: AddOrderSupplierPayload) -> Result<Order, Error>
 #[ic_cdk::update]
 fn complete_order(id: u64) -> Result<Order, Error> {
     // Try to get the order with the given ID
-    match ORDERS.with(|service| service.borrow().get(&id)) {
+    match _get_order(&id)? {
         Some(mut order) => {
-            if order.is_complete {
+            if order.status == OrderStatus::Delivered {
                 return Err(Error::AlreadyCompleted {
                     msg: format!("Order was already completed."),
                 });
@@ -576,16 +1017,34 @@ fn complete_order(id: u64) -> Result<Order, Error> {
                     msg: format!("Supplier with id={} not found.", order.supplier_id.unwrap()),
                 });
             }
-            // Mark the order as complete and update the timestamp
-            order.is_complete = true;
+            // A fresh order sitting in Pending or Assigned can be marked
+            // delivered directly, as a shortcut around walking `transition_order`
+            // through Shipped, so existing callers of `complete_order` keep
+            // working unchanged. Any other status reuses `_is_valid_transition`
+            // so this shortcut can't be used as a backdoor around the state
+            // machine (e.g. completing a Cancelled order).
+            let can_complete = match order.status {
+                OrderStatus::Pending | OrderStatus::Assigned => true,
+                _ => _is_valid_transition(order.status, OrderStatus::Delivered),
+            };
+            if !can_complete {
+                return Err(Error::InvalidTransition {
+                    msg: format!(
+                        "order id:{} cannot be completed from status {:?}",
+                        id, order.status
+                    ),
+                });
+            }
+            // Mark the order as delivered and update the timestamp.
+            order.status = OrderStatus::Delivered;
             order.updated_at = Some(time());
 
             // Insert the updated order back into the storage
             _insert_order(&order);
+            record_order_event(order.id, OrderEventKind::StatusChanged, &order)?;
+
+            _update_ids(order.clone())?; // Order is now complete; update client/supplier order IDs
 
-            if order.is_complete {
-                _update_ids(order.clone()) // Update IDs if the order is marked as complete
-            }
             Ok(order) // Return the completed order
         }
         None => Err(Error::NotFound {
@@ -593,13 +1052,72 @@ fn complete_order(id: u64) -> Result<Order, Error> {
         }), // Return an error if the order is not found
     }
 }
+
+// Completes many orders in one call. Each id is validated and applied
+// independently, so one bad entry doesn't abort the rest of the batch.
+#[ic_cdk::update]
+fn batch_complete_orders(ids: Vec<u64>) -> Vec<Result<Order, Error>> {
+    ids.into_iter().map(complete_order).collect()
+}
+
+// Moves an order through the guarded lifecycle table in `_is_valid_transition`
+// (Pending -> Assigned -> Shipped -> Delivered, with Cancelled/Disputed
+// branches). Unlike `complete_order`'s direct shortcut to Delivered, this
+// enforces the full pipeline for callers that model the intermediate states.
+#[ic_cdk::update]
+fn transition_order(id: u64, new_status: OrderStatus) -> Result<Order, Error> {
+    let mut order = match _get_order(&id)? {
+        Some(order) => order,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("order id:{} does not exist", id),
+            })
+        }
+    };
+
+    if new_status == OrderStatus::Assigned && order.supplier_id.is_none() {
+        return Err(Error::InvalidTransition {
+            msg: format!(
+                "order id:{} cannot move to Assigned before a supplier is set",
+                id
+            ),
+        });
+    }
+
+    if !_is_valid_transition(order.status, new_status) {
+        return Err(Error::InvalidTransition {
+            msg: format!(
+                "order id:{} cannot transition from {:?} to {:?}",
+                id, order.status, new_status
+            ),
+        });
+    }
+
+    order.status = new_status;
+    order.updated_at = Some(time());
+
+    _insert_order(&order);
+    record_order_event(order.id, OrderEventKind::StatusChanged, &order)?;
+
+    if order.status == OrderStatus::Delivered {
+        _update_ids(order.clone())?;
+    }
+
+    Ok(order)
+}
+
 // Function to update an order
 #[ic_cdk::update]
 fn update_order(id: u64, payload: OrderPayload) -> Result<Order, Error> {
     // Try to get the existing order with the given ID
-    let order = ORDERS
-        .with(|service| service.borrow().get(&id))
-        .expect("order does not exist");
+    let order = match _get_order(&id)? {
+        Some(order) => order,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("order id:{} does not exist", id),
+            })
+        }
+    };
 
     let check_payload = payload.validate();
     if check_payload.is_err() {
@@ -627,13 +1145,14 @@ fn update_order(id: u64, payload: OrderPayload) -> Result<Order, Error> {
         supplier_id: Some(payload.supplier_id),
         item_types: payload.items_types,
         products: payload.products,
-        is_complete: order.is_complete,
+        status: order.status,
         created_at: order.created_at,
         updated_at: Some(time()),
     };
 
     // Insert the updated order into the storage
     _insert_order(&updated_order);
+    record_order_event(updated_order.id, OrderEventKind::Updated, &updated_order)?;
 
     Ok(updated_order) // Return the updated order
 }
@@ -642,7 +1161,12 @@ fn update_order(id: u64, payload: OrderPayload) -> Result<Order, Error> {
 fn delete_order(id: u64) -> Result<Order, Error> {
     // Remove the order with the given ID from the storage
     match ORDERS.with(|orders| orders.borrow_mut().remove(&id)) {
-        Some(order) => Ok(order), // Return the deleted order
+        Some(bytes) => {
+            let order: Order = decode_record(&bytes)?;
+            _remove_order_indexes(&order);
+            record_order_event(order.id, OrderEventKind::Deleted, &order)?;
+            Ok(order) // Return the deleted order
+        }
         None => Err(Error::NotFound {
             msg: format!("Order id:{} deletion unsuccessful. Order Not found", id),
         }), // Return an error if the order is not found
@@ -651,28 +1175,336 @@ fn delete_order(id: u64) -> Result<Order, Error> {
 
 // Order Helper functions
 
-fn _get_order(id: &u64) -> Option<Order> {
+fn _get_order(id: &u64) -> Result<Option<Order>, Error> {
     // Get the order from the storage based on the provided ID
-    ORDERS.with(|orders| orders.borrow().get(&id))
+    match ORDERS.with(|orders| orders.borrow().get(id)) {
+        Some(bytes) => decode_record(&bytes).map(Some),
+        None => Ok(None),
+    }
 }
 
 fn _insert_order(order: &Order) {
+    // Drop the previous version's index entries first, since this same
+    // function is used for both creation and in-place updates (supplier
+    // assignment, completion, edits) and the indexed fields may have changed.
+    // A corrupted previous record is skipped rather than trapping: the worst
+    // case is a stale index entry, not lost or incorrect order data.
+    if let Some(previous) = ORDERS
+        .with(|orders| orders.borrow().get(&order.id))
+        .and_then(|bytes| decode_record::<Order>(&bytes).ok())
+    {
+        _remove_order_indexes(&previous);
+    }
+
     // Insert an order into the storage
-    ORDERS.with(|orders| orders.borrow_mut().insert(order.id, order.clone()));
+    ORDERS.with(|orders| orders.borrow_mut().insert(order.id, encode_record(order)));
+
+    _insert_order_indexes(order);
 }
 
-fn _update_ids(order: Order) {
-    // Checks were already made to check whether client exists
-    let mut client = get_client(order.client_id).ok().unwrap();
-    client.order_ids.push(order.id);
-    // Update the client's order IDs
-    CLIENT_STORAGE.with(|clients| clients.borrow_mut().insert(client.id, client.clone()));
+// Adds `order` to every secondary index it belongs in.
+fn _insert_order_indexes(order: &Order) {
+    CLIENT_ORDER_INDEX.with(|index| {
+        index.borrow_mut().insert(
+            ClientOrderKey {
+                client_id: order.client_id,
+                order_id: order.id,
+            },
+            order.id,
+        )
+    });
+
+    if let Some(supplier_id) = order.supplier_id {
+        SUPPLIER_ORDER_INDEX.with(|index| {
+            index.borrow_mut().insert(
+                SupplierOrderKey {
+                    supplier_id,
+                    is_delivered: order.status == OrderStatus::Delivered,
+                    order_id: order.id,
+                },
+                order.id,
+            )
+        });
+    }
+
+    for item_type in &order.item_types {
+        ITEM_TYPE_ORDER_INDEX.with(|index| {
+            index.borrow_mut().insert(
+                ItemTypeOrderKey {
+                    item_type: item_type.clone(),
+                    order_id: order.id,
+                },
+                order.id,
+            )
+        });
+    }
+
+    COMPLETION_ORDER_INDEX.with(|index| {
+        index.borrow_mut().insert(
+            CompletionOrderKey {
+                is_delivered: order.status == OrderStatus::Delivered,
+                order_id: order.id,
+            },
+            order.id,
+        )
+    });
+}
+
+// Removes `order` from every secondary index it belongs in.
+fn _remove_order_indexes(order: &Order) {
+    CLIENT_ORDER_INDEX.with(|index| {
+        index.borrow_mut().remove(&ClientOrderKey {
+            client_id: order.client_id,
+            order_id: order.id,
+        })
+    });
+
+    if let Some(supplier_id) = order.supplier_id {
+        SUPPLIER_ORDER_INDEX.with(|index| {
+            index.borrow_mut().remove(&SupplierOrderKey {
+                supplier_id,
+                is_delivered: order.status == OrderStatus::Delivered,
+                order_id: order.id,
+            })
+        });
+    }
+
+    for item_type in &order.item_types {
+        ITEM_TYPE_ORDER_INDEX.with(|index| {
+            index.borrow_mut().remove(&ItemTypeOrderKey {
+                item_type: item_type.clone(),
+                order_id: order.id,
+            })
+        });
+    }
+
+    COMPLETION_ORDER_INDEX.with(|index| {
+        index.borrow_mut().remove(&CompletionOrderKey {
+            is_delivered: order.status == OrderStatus::Delivered,
+            order_id: order.id,
+        })
+    });
+}
+
+// Order provenance log
+
+fn next_event_seq() -> Result<u64, Error> {
+    EVENT_SEQ_COUNTER
+        .with(|counter| {
+            let next = *counter.borrow().get() + 1;
+            counter.borrow_mut().set(next).map(|_| next)
+        })
+        .map_err(|_| Error::Corruption {
+            msg: "failed to persist the event sequence counter".to_string(),
+        })
+}
+
+// Appends an `OrderEvent` for `state`, and every `KEEP_STATE_EVERY` events
+// for the same order writes a fresh `Checkpoint` so `get_order_at` never has
+// to replay more than that many deltas.
+fn record_order_event(order_id: u64, kind: OrderEventKind, state: &Order) -> Result<(), Error> {
+    let seq = next_event_seq()?;
+    let event = OrderEvent {
+        seq,
+        order_id,
+        timestamp: time(),
+        kind,
+        snapshot_delta: state.clone(),
+    };
+
+    ORDER_EVENTS.with(|log| log.borrow_mut().insert(seq, event));
+    ORDER_EVENT_INDEX.with(|index| {
+        index.borrow_mut().insert(OrderEventKey { order_id, seq }, seq)
+    });
+
+    let events_since_checkpoint = ORDER_EVENTS_SINCE_CHECKPOINT.with(|counts| {
+        let count = counts.borrow().get(&order_id).unwrap_or(0) + 1;
+        counts.borrow_mut().insert(order_id, count);
+        count
+    });
+
+    if events_since_checkpoint >= KEEP_STATE_EVERY {
+        ORDER_CHECKPOINTS.with(|checkpoints| {
+            checkpoints.borrow_mut().insert(
+                OrderEventKey { order_id, seq },
+                Checkpoint {
+                    seq,
+                    order_id,
+                    state: state.clone(),
+                },
+            )
+        });
+        ORDER_EVENTS_SINCE_CHECKPOINT.with(|counts| counts.borrow_mut().insert(order_id, 0));
+    }
+
+    Ok(())
+}
+
+// Latest checkpoint for `order_id` at or before `seq`, if one has been written yet.
+fn _latest_checkpoint_at_or_before(order_id: u64, seq: u64) -> Option<Checkpoint> {
+    let start = OrderEventKey { order_id, seq: 0 };
+    let end = OrderEventKey { order_id, seq };
+    ORDER_CHECKPOINTS.with(|checkpoints| {
+        checkpoints
+            .borrow()
+            .range(start..=end)
+            .last()
+            .map(|(_, checkpoint)| checkpoint)
+    })
+}
+
+// Ordered event sequence numbers recorded for `order_id` within `[from, to]`.
+fn _order_event_seqs(order_id: u64, from: u64, to: u64) -> Vec<u64> {
+    let start = OrderEventKey { order_id, seq: from };
+    let end = OrderEventKey { order_id, seq: to };
+    ORDER_EVENT_INDEX.with(|index| {
+        index
+            .borrow()
+            .range(start..=end)
+            .map(|(_, seq)| seq)
+            .collect()
+    })
+}
+
+#[ic_cdk::query]
+fn get_order_history(order_id: u64) -> Result<Vec<OrderEvent>, Error> {
+    let events: Vec<OrderEvent> = _order_event_seqs(order_id, 0, u64::MAX)
+        .into_iter()
+        .filter_map(|seq| ORDER_EVENTS.with(|log| log.borrow().get(&seq)))
+        .collect();
+
+    if events.is_empty() {
+        Err(Error::NotFound {
+            msg: format!("No history available for order id:{}", order_id),
+        })
+    } else {
+        Ok(events)
+    }
+}
+
+#[ic_cdk::query]
+fn get_order_at(order_id: u64, seq: u64) -> Result<Order, Error> {
+    let checkpoint = _latest_checkpoint_at_or_before(order_id, seq);
+    let (mut state, from_seq, has_baseline) = match checkpoint {
+        Some(checkpoint) => (checkpoint.state, checkpoint.seq, true),
+        None => (Order::default(), 0, false),
+    };
+
+    let deltas: Vec<OrderEvent> = _order_event_seqs(order_id, from_seq + 1, seq)
+        .into_iter()
+        .filter_map(|seq| ORDER_EVENTS.with(|log| log.borrow().get(&seq)))
+        .collect();
+    let replayed_any = !deltas.is_empty();
+    for delta in deltas {
+        state = delta.snapshot_delta;
+    }
+
+    if has_baseline || replayed_any {
+        Ok(state)
+    } else {
+        Err(Error::NotFound {
+            msg: format!(
+                "No recorded state for order id:{} at or before seq:{}",
+                order_id, seq
+            ),
+        })
+    }
+}
+
+// Prunes, per order, the events older than that order's latest checkpoint
+// at or before `before_seq`, collapsing them into that checkpoint; events
+// between the checkpoint and `before_seq` are kept, and an order with no
+// such checkpoint is left untouched. Checking each event
+// independently against "does *any* later checkpoint exist" is wrong: with
+// checkpoints at seq 64 and 128, an event at seq 70 would look covered by
+// the checkpoint at 128 and get deleted, but `get_order_at(order_id, 90)`
+// still needs checkpoint 64 plus that event to replay correctly, and would
+// silently return stale state once it's gone. So instead, for each order,
+// find the single newest checkpoint at or before `before_seq` (`keep`) and
+// drop every *earlier* checkpoint for that order along with every event
+// strictly before `keep` as one contiguous unit — `keep`'s own snapshot
+// already captures their cumulative effect, and no stale earlier checkpoint
+// is left behind to be picked as a baseline for deltas that no longer exist.
+// Returns the number of events removed.
+#[ic_cdk::update]
+fn compact_log(before_seq: u64) -> u64 {
+    let order_ids: std::collections::HashSet<u64> = ORDER_CHECKPOINTS.with(|checkpoints| {
+        checkpoints
+            .borrow()
+            .iter()
+            .map(|(key, _)| key.order_id)
+            .collect()
+    });
+
+    let mut removed = 0u64;
+    for order_id in order_ids {
+        let start = OrderEventKey { order_id, seq: 0 };
+        let end = OrderEventKey {
+            order_id,
+            seq: before_seq,
+        };
+        let checkpoints_at_or_before: Vec<OrderEventKey> = ORDER_CHECKPOINTS.with(|checkpoints| {
+            checkpoints
+                .borrow()
+                .range(start..=end)
+                .map(|(key, _)| key)
+                .collect()
+        });
+        let Some(keep) = checkpoints_at_or_before.last().copied() else {
+            continue; // No checkpoint at or before `before_seq` yet for this order.
+        };
+
+        for stale_checkpoint in &checkpoints_at_or_before[..checkpoints_at_or_before.len() - 1] {
+            ORDER_CHECKPOINTS.with(|checkpoints| checkpoints.borrow_mut().remove(stale_checkpoint));
+        }
+
+        let stale_events: Vec<u64> = ORDER_EVENT_INDEX.with(|index| {
+            index
+                .borrow()
+                .range(OrderEventKey { order_id, seq: 0 }..OrderEventKey { order_id, seq: keep.seq })
+                .map(|(_, seq)| seq)
+                .collect()
+        });
+        for seq in stale_events {
+            ORDER_EVENTS.with(|log| log.borrow_mut().remove(&seq));
+            ORDER_EVENT_INDEX.with(|index| {
+                index.borrow_mut().remove(&OrderEventKey { order_id, seq })
+            });
+            removed += 1;
+        }
+    }
+
+    removed
+}
+
+// Records a now-delivered order against its client and supplier. An order
+// can re-enter `Delivered` more than once (e.g. `Delivered -> Disputed ->
+// Delivered`), so pushing unconditionally would append duplicate order ids
+// each time; only push when the id isn't already recorded.
+fn _update_ids(order: Order) -> Result<(), Error> {
+    // Checks were already made to check whether client exists, but storage reads
+    // can still fail if the record was corrupted in the meantime, so propagate.
+    let mut client = get_client(order.client_id)?;
+    if !client.order_ids.contains(&order.id) {
+        client.order_ids.push(order.id);
+        // Update the client's order IDs
+        CLIENT_STORAGE
+            .with(|clients| clients.borrow_mut().insert(client.id, encode_record(&client)));
+    }
 
     // Checks were already made to check whether supplier exists
-    let mut supplier = get_supplier(order.supplier_id.unwrap()).ok().unwrap();
-    supplier.order_ids.push(order.id);
-    // Update the supplier's order IDs
-    SUPPLIER_STORAGE.with(|suppliers| suppliers.borrow_mut().insert(supplier.id, supplier.clone()));
+    let mut supplier = get_supplier(order.supplier_id.unwrap())?;
+    if !supplier.order_ids.contains(&order.id) {
+        supplier.order_ids.push(order.id);
+        // Update the supplier's order IDs
+        SUPPLIER_STORAGE.with(|suppliers| {
+            suppliers
+                .borrow_mut()
+                .insert(supplier.id, encode_record(&supplier))
+        });
+    }
+
+    Ok(())
 }
 
 // Helper function to check whether a client with client_id exists
@@ -698,8 +1530,57 @@ fn is_supplier_id_valid(supplier_id: &u64) -> bool {
 enum Error {
     NotFound { msg: String },
     InvalidPayload { msg: String },
-    AlreadyCompleted { msg: String}
+    AlreadyCompleted { msg: String },
+    Corruption { msg: String },
+    InvalidTransition { msg: String },
 }
 
 // Candid generator for exporting the Candid interface
 ic_cdk::export_candid!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Regression test for a `StableBTreeMap::insert` trap: the secondary
+    // order indexes and the order event log are keyed by candid-encoded
+    // structs whose `MAX_SIZE` must cover the DIDL-encoded size, not just
+    // the packed fields. This exercises every index (client, supplier,
+    // item type, completion) and the event log on the first order ever
+    // created, so an undersized `MAX_SIZE` fails loudly here instead of
+    // only trapping the canister in production.
+    #[test]
+    fn add_order_does_not_trap_on_secondary_index_inserts() {
+        let client = add_client(ClientPayload {
+            name: "Acme".to_string(),
+            email: "acme@example.com".to_string(),
+            phone: "1234567".to_string(),
+        })
+        .expect("add_client should succeed");
+
+        let supplier = add_supplier(SupplierPayload {
+            name: "Acme Supplies".to_string(),
+            email: "supplies@example.com".to_string(),
+            phone: "7654321".to_string(),
+            prefered_items: vec!["widget".to_string()],
+        })
+        .expect("add_supplier should succeed");
+
+        let order = add_order(OrderPayload {
+            title: "Widgets".to_string(),
+            client_id: client.id,
+            supplier_id: 0,
+            products: HashMap::new(),
+            items_types: vec!["widget".to_string()],
+        })
+        .expect("add_order should succeed without trapping on the secondary indexes");
+
+        let order = add_order_supplier(AddOrderSupplierPayload {
+            order_id: order.id,
+            supplier_id: supplier.id,
+        })
+        .expect("add_order_supplier should succeed without trapping on the supplier index");
+
+        assert_eq!(get_order(order.id).unwrap().supplier_id, Some(supplier.id));
+    }
+}